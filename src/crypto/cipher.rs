@@ -0,0 +1,64 @@
+//! AEAD sealing/opening over a 96-bit nonce.
+
+use chacha20poly1305::{
+    aead::{Aead as _, KeyInit, Payload},
+    ChaCha20Poly1305, Nonce,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CipherError {
+    UnknownPeer,
+    AuthenticationFailed,
+    /// The nonce/counter fell outside the peer's sliding replay window — a
+    /// replayed or duplicated message, not merely one that arrived out of
+    /// order.
+    ReplayedNonce,
+}
+
+/// Thin wrapper so the rest of the crypto module doesn't depend on a
+/// specific AEAD crate directly.
+pub struct Aead {
+    cipher: ChaCha20Poly1305,
+}
+
+impl Aead {
+    pub fn chacha20poly1305(key: &[u8; 32]) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(key.into()),
+        }
+    }
+
+    pub fn seal(
+        &self,
+        nonce: &[u8; 12],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, CipherError> {
+        self.cipher
+            .encrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .map_err(|_| CipherError::AuthenticationFailed)
+    }
+
+    pub fn open(
+        &self,
+        nonce: &[u8; 12],
+        aad: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, CipherError> {
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| CipherError::AuthenticationFailed)
+    }
+}
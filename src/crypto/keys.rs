@@ -0,0 +1,93 @@
+//! Static identities and peer trust for the encryption layer.
+
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// How a node's long-term keypair is established.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrustMode {
+    /// The keypair is derived from a shared passphrase; every peer that
+    /// knows the passphrase derives the same static key and implicitly
+    /// trusts it.
+    SharedSecret,
+    /// The keypair is random; peers exchange public keys out of band and
+    /// pin them explicitly via [`PeerStore::trust`].
+    ExplicitTrust,
+}
+
+/// A node's long-term X25519 identity.
+pub struct StaticKeypair {
+    pub mode: TrustMode,
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl StaticKeypair {
+    /// Derives a static keypair from a shared passphrase (`TrustMode::SharedSecret`).
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(b"ppr-test/shared-secret/v1");
+        hasher.update(passphrase.as_bytes());
+        let digest: [u8; 32] = hasher.finalize().into();
+
+        let secret = StaticSecret::from(digest);
+        let public = PublicKey::from(&secret);
+
+        Self {
+            mode: TrustMode::SharedSecret,
+            secret,
+            public,
+        }
+    }
+
+    /// Generates a random static keypair (`TrustMode::ExplicitTrust`); the
+    /// public half must be shared with peers out of band before a session
+    /// can be established.
+    pub fn generate() -> Self {
+        let secret = StaticSecret::random_from_rng(rand::rngs::OsRng);
+        let public = PublicKey::from(&secret);
+
+        Self {
+            mode: TrustMode::ExplicitTrust,
+            secret,
+            public,
+        }
+    }
+
+    pub fn public(&self) -> PublicKey {
+        self.public
+    }
+
+    pub(super) fn diffie_hellman(&self, their_public: &PublicKey) -> [u8; 32] {
+        self.secret.diffie_hellman(their_public).to_bytes()
+    }
+}
+
+/// Public keys of peers this node is willing to establish a session with,
+/// indexed by `service_id`.
+#[derive(Default)]
+pub struct PeerStore {
+    trusted: std::collections::HashMap<u16, PublicKey>,
+}
+
+impl PeerStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// In `TrustMode::SharedSecret`, a peer trusts its own derived key.
+    pub fn trust_self(mut self, service_id: u16, keypair: &StaticKeypair) -> Self {
+        self.trusted.insert(service_id, keypair.public());
+        self
+    }
+
+    /// In `TrustMode::ExplicitTrust`, pin a peer's public key exchanged out of band.
+    pub fn trust(mut self, service_id: u16, public: PublicKey) -> Self {
+        self.trusted.insert(service_id, public);
+        self
+    }
+
+    pub fn get(&self, service_id: u16) -> Option<&PublicKey> {
+        self.trusted.get(&service_id)
+    }
+}
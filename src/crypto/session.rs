@@ -0,0 +1,194 @@
+//! Per-peer session key derivation, nonce bookkeeping, and rekeying.
+//!
+//! A plain ECDH shared secret is symmetric, so deriving one traffic key from
+//! it and using it for both outgoing and incoming messages would make both
+//! peers encrypt under the identical key — the first message each side sends
+//! would then reuse (key, nonce) = (traffic_key, 0) across directions, which
+//! breaks ChaCha20-Poly1305 outright. [`SessionKeys`] instead derives two
+//! directional root keys (`send_root`/`recv_root`, Noise's `c1`/`c2` split)
+//! by salting the HKDF info string with the ordered (sender, receiver)
+//! public keys, so a peer's send key always matches the other side's recv
+//! key.
+//!
+//! Because traffic is connectionless and reorderable, neither replay
+//! rejection nor rekeying can be driven by *local* bookkeeping of how many
+//! messages have actually arrived — a dropped or delayed message would
+//! desync that count from the sender's. [`SessionKeys::accept_nonce`]
+//! instead tracks a sliding window of recently accepted nonces rather than
+//! demanding strict monotonicity, and the traffic key used for a given
+//! message is derived purely as a function of its header nonce (see
+//! [`SessionKeys::send_key`]/[`SessionKeys::recv_key`]) rather than advanced
+//! by a counter that could fall out of step with the peer's.
+
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::PublicKey;
+
+use super::keys::StaticKeypair;
+
+/// After how many messages a session moves to a freshly derived traffic key.
+/// Because the key for a message is derived directly from its nonce (see
+/// [`SessionKeys::send_key`]), this is a pure, stateless function of the
+/// wire nonce rather than a count of messages actually sent or received, so
+/// drops and reordering can never desync the two ends' idea of which key
+/// generation a message belongs to.
+#[derive(Debug, Clone, Copy)]
+pub struct RekeyPolicy {
+    pub max_messages: u64,
+}
+
+impl Default for RekeyPolicy {
+    fn default() -> Self {
+        Self {
+            max_messages: 10_000,
+        }
+    }
+}
+
+/// Width of the anti-replay window tracked alongside `highest_seen_nonce`:
+/// a nonce less than this far behind the highest one seen is still accepted (and
+/// marked off) the first time it arrives, so a message delayed or reordered
+/// behind up to `REPLAY_WINDOW - 1` later messages still decrypts. Anything
+/// older than that is indistinguishable from a replay and is rejected.
+const REPLAY_WINDOW: u64 = 64;
+
+/// Live traffic key state for one peer, identified by `service_id`.
+pub struct SessionKeys {
+    /// Generation-0 directional keys. The key actually used to seal/open a
+    /// given message is derived from whichever of these corresponds to its
+    /// direction, salted with that message's rekey generation — see
+    /// [`SessionKeys::send_key`]/[`SessionKeys::recv_key`].
+    send_root: [u8; 32],
+    recv_root: [u8; 32],
+    send_counter: u64,
+    /// Highest nonce accepted from this peer so far; `None` until the first
+    /// message is opened.
+    highest_seen_nonce: Option<u64>,
+    /// Bitmap over the `REPLAY_WINDOW` nonces at and below
+    /// `highest_seen_nonce`: bit `i` is set once the nonce `highest - i` has
+    /// been accepted, so a duplicate or replay of it is rejected even though
+    /// it isn't the highest nonce seen.
+    replay_window: u64,
+    max_messages: u64,
+}
+
+impl SessionKeys {
+    /// Derives the initial directional root keys via ECDH + HKDF-SHA256,
+    /// Noise-style.
+    pub fn derive(local: &StaticKeypair, peer_public: &PublicKey, policy: RekeyPolicy) -> Self {
+        let shared = local.diffie_hellman(peer_public);
+        let hk = Hkdf::<Sha256>::new(Some(b"ppr-test/handshake/v1"), &shared);
+
+        let local_public = local.public();
+        let send_root = directional_key(&hk, local_public.as_bytes(), peer_public.as_bytes());
+        let recv_root = directional_key(&hk, peer_public.as_bytes(), local_public.as_bytes());
+
+        Self {
+            send_root,
+            recv_root,
+            send_counter: 0,
+            highest_seen_nonce: None,
+            replay_window: 0,
+            max_messages: policy.max_messages,
+        }
+    }
+
+    /// The key to seal a message carrying nonce `counter`, derived from the
+    /// send root and that nonce's rekey generation (`counter /
+    /// max_messages`). Deterministic in `counter` alone, so it doesn't
+    /// matter whether earlier messages were ever actually delivered.
+    pub fn send_key(&self, counter: u64) -> [u8; 32] {
+        key_at_generation(&self.send_root, self.generation(counter))
+    }
+
+    /// The key to open a message carrying nonce `counter`; see
+    /// [`SessionKeys::send_key`].
+    pub fn recv_key(&self, counter: u64) -> [u8; 32] {
+        key_at_generation(&self.recv_root, self.generation(counter))
+    }
+
+    fn generation(&self, counter: u64) -> u64 {
+        counter / self.max_messages.max(1)
+    }
+
+    /// Returns the next nonce/counter value to place in the message header
+    /// and advances the send counter. Because the counter travels with the
+    /// message instead of being inferred from arrival order, out-of-order
+    /// or dropped messages still decrypt.
+    pub fn next_nonce(&mut self) -> u64 {
+        let nonce = self.send_counter;
+        self.send_counter += 1;
+        nonce
+    }
+
+    /// Records `counter` as seen from this peer using a sliding replay
+    /// window, rejecting it only if it's an outright replay or duplicate —
+    /// not merely out of arrival order. A counter above the highest seen so
+    /// far always advances the window; one within `REPLAY_WINDOW` behind it
+    /// is accepted the first time and rejected on every subsequent repeat;
+    /// one further behind than that no longer has a tracked bit and is
+    /// rejected outright.
+    pub fn accept_nonce(&mut self, counter: u64) -> bool {
+        match self.highest_seen_nonce {
+            None => {
+                self.highest_seen_nonce = Some(counter);
+                self.replay_window = 1;
+                true
+            }
+            Some(highest) if counter > highest => {
+                let advance = counter - highest;
+                self.replay_window = if advance >= REPLAY_WINDOW {
+                    1
+                } else {
+                    (self.replay_window << advance) | 1
+                };
+                self.highest_seen_nonce = Some(counter);
+                true
+            }
+            Some(highest) => {
+                let age = highest - counter;
+                if age >= REPLAY_WINDOW {
+                    return false;
+                }
+                let bit = 1u64 << age;
+                if self.replay_window & bit != 0 {
+                    return false;
+                }
+                self.replay_window |= bit;
+                true
+            }
+        }
+    }
+}
+
+/// Expands one directional root key, salting the info string with the
+/// ordered (sender, receiver) public keys so the two ends of a session never
+/// derive the same key for both directions.
+fn directional_key(hk: &Hkdf<Sha256>, from_public: &[u8], to_public: &[u8]) -> [u8; 32] {
+    let mut info = Vec::with_capacity(b"traffic key".len() + from_public.len() + to_public.len());
+    info.extend_from_slice(b"traffic key");
+    info.extend_from_slice(from_public);
+    info.extend_from_slice(to_public);
+
+    let mut key = [0u8; 32];
+    hk.expand(&info, &mut key)
+        .expect("32 bytes is a valid HKDF output length");
+    key
+}
+
+/// Derives the key for rekey `generation` directly from a direction's root
+/// key. Every generation is independently derivable from the root in one
+/// step (rather than by chaining generation 0 -> 1 -> 2 -> ...), so deriving
+/// the key for a message never depends on whether the generations before it
+/// were ever actually seen.
+fn key_at_generation(root: &[u8; 32], generation: u64) -> [u8; 32] {
+    if generation == 0 {
+        return *root;
+    }
+
+    let hk = Hkdf::<Sha256>::new(Some(b"ppr-test/rekey/v1"), root);
+    let mut key = [0u8; 32];
+    hk.expand(&generation.to_be_bytes(), &mut key)
+        .expect("32 bytes is a valid HKDF output length");
+    key
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+
+/// Errors that can occur while decoding a [`crate::Message`] from the wire.
+#[derive(Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum DecodeError {
+    /// The buffer does not yet hold a full frame; ask the caller for more bytes.
+    ShortRead,
+    /// A length field (`signature_length`/`payload_length`) is inconsistent with the framed body.
+    BadLengthDesc,
+    /// The `version` byte is not one this codec understands.
+    UnknownVersion(u8),
+    /// The signature did not verify against the key trusted for the
+    /// message's `service_id`, or no key is trusted for it.
+    BadSignature,
+    /// A message with `encrypted != 0` could not be decrypted: no encryption
+    /// context is configured, no peer is trusted for its `service_id`, its
+    /// nonce was replayed, or authenticated decryption failed.
+    DecryptionFailed,
+    /// `features` sets a required (even-indexed) bit this codec doesn't
+    /// list in `MessageCodec::supported_features`. The value is the subset
+    /// of unsupported bits that were required.
+    UnknownRequiredFeature(u32),
+    /// The underlying transport reported an I/O error while reading.
+    Io(std::io::ErrorKind),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::ShortRead => write!(f, "buffer does not yet contain a full frame"),
+            DecodeError::BadLengthDesc => write!(
+                f,
+                "signature_length/payload_length is inconsistent with the framed body"
+            ),
+            DecodeError::UnknownVersion(version) => {
+                write!(f, "unknown protocol version {version}")
+            }
+            DecodeError::BadSignature => write!(f, "signature verification failed"),
+            DecodeError::DecryptionFailed => write!(
+                f,
+                "decryption failed (no encryption context or trusted peer, replayed nonce, or authentication failure)"
+            ),
+            DecodeError::UnknownRequiredFeature(bits) => {
+                write!(f, "message requires unsupported feature bits {bits:#010x}")
+            }
+            DecodeError::Io(kind) => write!(f, "I/O error: {kind}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for std::io::Error {
+    fn from(err: DecodeError) -> Self {
+        match err {
+            DecodeError::ShortRead => {
+                std::io::Error::new(std::io::ErrorKind::WouldBlock, err.to_string())
+            }
+            DecodeError::Io(kind) => std::io::Error::new(kind, err.to_string()),
+            _ => std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()),
+        }
+    }
+}
+
+/// `tokio_util::codec::Decoder::Error` requires this so I/O errors from the
+/// underlying transport (not just malformed frames) can propagate as a
+/// `DecodeError`.
+impl From<std::io::Error> for DecodeError {
+    fn from(err: std::io::Error) -> Self {
+        DecodeError::Io(err.kind())
+    }
+}
+
+/// Errors that can occur while encoding a [`crate::Message`] onto the wire.
+#[derive(Debug, PartialEq, Eq)]
+pub enum EncodeError {
+    /// `signature_length` does not match `signature.len()`.
+    SignatureLengthMismatch { expected: u32, actual: usize },
+    /// `encrypted != 0` on the message but the codec has no encryption
+    /// context, or the context could not encrypt for the message's
+    /// `service_id` (for instance, no peer is trusted for it).
+    EncryptionNotConfigured,
+    /// The underlying transport reported an I/O error while writing.
+    Io(std::io::ErrorKind),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::SignatureLengthMismatch { expected, actual } => write!(
+                f,
+                "signature_length ({expected}) does not match signature.len() ({actual})"
+            ),
+            EncodeError::EncryptionNotConfigured => {
+                write!(f, "message is marked encrypted but could not be encrypted for its service_id")
+            }
+            EncodeError::Io(kind) => write!(f, "I/O error: {kind}"),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+impl From<EncodeError> for std::io::Error {
+    fn from(err: EncodeError) -> Self {
+        match err {
+            EncodeError::Io(kind) => std::io::Error::new(kind, err.to_string()),
+            _ => std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()),
+        }
+    }
+}
+
+/// `tokio_util::codec::Encoder::Error` requires this so I/O errors from the
+/// underlying transport (not just malformed messages) can propagate as an
+/// `EncodeError`.
+impl From<std::io::Error> for EncodeError {
+    fn from(err: std::io::Error) -> Self {
+        EncodeError::Io(err.kind())
+    }
+}
@@ -0,0 +1,35 @@
+//! Feature-bit negotiation for `Message::features`, using the even/odd
+//! convention: an even-indexed bit is *required* (the receiver must
+//! understand it to safely process the message, and rejects the message if
+//! it doesn't), while an odd-indexed bit is *optional* and may be silently
+//! ignored by a receiver that doesn't recognize it. This mirrors the
+//! convention used by the Lightning Network's BOLT #9 feature bits.
+
+/// Every even-indexed bit (0, 2, 4, ...) — the required half of the feature
+/// space.
+const REQUIRED_BITS_MASK: u32 = 0x5555_5555;
+
+/// Set when the message was framed with
+/// [`crate::MessageCodec::with_compact_lengths`]. Deliberately an
+/// *optional* (odd-indexed) bit, even though a receiver that disagrees on
+/// `compact_lengths` genuinely can't parse the header: `signature_length`
+/// is the very first wire field, framed according to the receiver's own
+/// (local, out-of-band-agreed) `compact_lengths` setting, long before
+/// `features` is readable. By the time a mismatch could in principle be
+/// detected, `signature_length` has already been misparsed and corrupted
+/// every field after it — so marking this bit "required" would promise an
+/// in-band safety net this mechanism cannot deliver. It exists so a
+/// correctly-parsed message can still record which framing its sender used.
+pub const COMPACT_LENGTHS: u32 = 1 << 1;
+
+/// Set on a message encoded with `encrypted != 0`. Required: a receiver
+/// that doesn't decrypt would hand the caller ciphertext instead of
+/// plaintext.
+pub const ENCRYPTED: u32 = 1 << 2;
+
+/// Returns the subset of `features` that are both required (even-indexed)
+/// and absent from `supported` — i.e. the bits that should make decoding
+/// fail.
+pub fn unknown_required(features: u32, supported: u32) -> u32 {
+    features & REQUIRED_BITS_MASK & !supported
+}
@@ -0,0 +1,119 @@
+//! `Reader`/`Writer` abstraction over the header+payload framing.
+//!
+//! Routing field access through these two traits instead of hard-coding it
+//! to `bytes::BytesMut` lets the same framing logic (see [`crate::schema`])
+//! run against an in-memory slice, an async `BytesMut`, or a blocking
+//! [`std::io::Read`] — whichever the caller has on hand.
+
+use std::io;
+
+use crate::error::DecodeError;
+
+/// A source of bytes for decoding one frame.
+///
+/// `read_exact` reports "not enough data available right now" as
+/// [`DecodeError::ShortRead`] rather than an I/O error, so callers (in
+/// particular [`crate::MessageCodec::decode`]) can treat it as "ask again
+/// once more bytes arrive" instead of a malformed frame.
+pub trait Reader {
+    fn read_exact(&mut self, len: usize) -> Result<Vec<u8>, DecodeError>;
+
+    fn read_u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.read_exact(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, DecodeError> {
+        let bytes = self.read_exact(2)?;
+        Ok(u16::from_le_bytes(
+            bytes.try_into().expect("read_exact(2) returns 2 bytes"),
+        ))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, DecodeError> {
+        let bytes = self.read_exact(4)?;
+        Ok(u32::from_le_bytes(
+            bytes.try_into().expect("read_exact(4) returns 4 bytes"),
+        ))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, DecodeError> {
+        let bytes = self.read_exact(8)?;
+        Ok(u64::from_le_bytes(
+            bytes.try_into().expect("read_exact(8) returns 8 bytes"),
+        ))
+    }
+}
+
+/// A destination for encoding one frame.
+pub trait Writer {
+    fn write_bytes(&mut self, bytes: &[u8]);
+
+    fn write_u8(&mut self, value: u8) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+}
+
+impl Reader for &[u8] {
+    fn read_exact(&mut self, len: usize) -> Result<Vec<u8>, DecodeError> {
+        if self.len() < len {
+            return Err(DecodeError::ShortRead);
+        }
+        let (consumed, rest) = self.split_at(len);
+        *self = rest;
+        Ok(consumed.to_vec())
+    }
+}
+
+impl Writer for Vec<u8> {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+impl Reader for bytes::BytesMut {
+    fn read_exact(&mut self, len: usize) -> Result<Vec<u8>, DecodeError> {
+        if self.len() < len {
+            return Err(DecodeError::ShortRead);
+        }
+        Ok(self.split_to(len).to_vec())
+    }
+}
+
+impl Writer for bytes::BytesMut {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+/// Adapts a blocking [`std::io::Read`] into a [`Reader`]. An unexpected EOF
+/// or a would-block (the stream just doesn't have the rest of the frame yet)
+/// is reported as [`DecodeError::ShortRead`], the same "ask again later"
+/// signal the in-memory readers use. Any other error kind (broken pipe,
+/// permission denied, ...) is a genuine transport failure, not a truncated
+/// frame, and is reported as [`DecodeError::Io`] so a caller that retries on
+/// `ShortRead` doesn't spin forever against a socket that has actually
+/// errored out.
+pub struct IoReader<T>(pub T);
+
+impl<T: io::Read> Reader for IoReader<T> {
+    fn read_exact(&mut self, len: usize) -> Result<Vec<u8>, DecodeError> {
+        let mut buf = vec![0u8; len];
+        self.0.read_exact(&mut buf).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof | io::ErrorKind::WouldBlock => DecodeError::ShortRead,
+            kind => DecodeError::Io(kind),
+        })?;
+        Ok(buf)
+    }
+}
@@ -1,105 +1,303 @@
+// This binary's wire-format logic (codec, crypto, signing) is exercised
+// almost entirely by the test suite below rather than by `main`, so most of
+// it looks unused to the `dead_code` lint outside `cargo test`.
+#![allow(dead_code)]
+
 #[derive(Debug)]
-#[allow(dead_code)]
 struct Message {
     signature_length: u32,
     version: u8,
+    /// Capability bits, even-indexed (required) vs. odd-indexed (optional);
+    /// see [`features`]. `MessageCodec::decode` rejects a message whose
+    /// required bits it doesn't list in `supported_features`.
+    features: u32,
     message_type: u16,
     service_id: u16,
     payload_length: u32,
     encrypted: u16,
+    /// Per-session counter. When `encrypted != 0` this doubles as the AEAD
+    /// nonce, which is why it travels explicitly instead of being inferred
+    /// from arrival order (see [`crypto`]).
+    nonce: u64,
     data: Vec<u8>,
     signature: Vec<u8>,
 }
 
+mod crypto;
+mod error;
+mod features;
+mod io;
+mod schema;
+mod signing;
+mod varint;
+
 use bytes::{Buf, BytesMut};
+use error::{DecodeError, EncodeError};
+use io::{Reader, Writer};
+use schema::define_header;
 use tokio_util::codec::{Decoder, Encoder};
 
+impl Message {
+    // Declares `write_header`/`read_header` from the wire layout below,
+    // instead of hand-coding each field's offset. `length` fields are framed
+    // per `MessageCodec::compact_lengths`; `data`/`signature` aren't listed
+    // here since their sizes are derived, not fixed-width (see
+    // `MessageCodec::try_decode`).
+    define_header! {
+        fields {
+            length signature_length: u32,
+            scalar version: u8,
+            scalar features: u32,
+            scalar message_type: u16,
+            scalar service_id: u16,
+            length payload_length: u32,
+            scalar encrypted: u16,
+            scalar nonce: u64,
+        }
+    }
+}
+
 #[allow(dead_code)]
-struct MessageCodec {}
+struct MessageCodec {
+    encryption: Option<crypto::EncryptionContext>,
+    signing: Option<signing::SigningContext>,
+    /// When set, `signature_length`/`payload_length` are framed as BigSize
+    /// varints instead of fixed 4-byte integers. Fixed-width peers are
+    /// unaffected until both sides negotiate the compact form.
+    compact_lengths: bool,
+    /// Feature bits this codec understands; see [`features`]. Only the
+    /// required (even-indexed) ones affect decoding: a decoded message whose
+    /// `features` requires a bit
+    /// outside this set is rejected with `DecodeError::UnknownRequiredFeature`.
+    supported_features: u32,
+}
 
 impl MessageCodec {
-    const SIZE_OF_WITHOUT_VECS: usize =
-        2 * std::mem::size_of::<u32>() + 3 * std::mem::size_of::<u16>() + std::mem::size_of::<u8>();
+    /// Size of the fixed-width header fields when `compact_lengths` is off.
+    const SIZE_OF_WITHOUT_VECS: usize = 3 * std::mem::size_of::<u32>()
+        + 3 * std::mem::size_of::<u16>()
+        + std::mem::size_of::<u8>()
+        + std::mem::size_of::<u64>();
 
-    fn new() -> Self {
-        Self {}
-    }
-}
+    /// A `payload_length` or `signature_length` larger than this is rejected as malformed
+    /// rather than trusted to allocate for. Each field is checked on its own.
+    const MAX_FRAME_BODY: usize = 16 * 1024 * 1024;
 
-impl Decoder for MessageCodec {
-    type Item = Message;
-    type Error = std::io::Error;
+    /// The only `version` this codec understands. A decoded message
+    /// carrying any other value is rejected with `DecodeError::UnknownVersion`
+    /// rather than parsed against a layout it wasn't written for.
+    const PROTOCOL_VERSION: u8 = 5;
 
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        if src.len() < Self::SIZE_OF_WITHOUT_VECS {
-            return Ok(None);
+    fn new() -> Self {
+        Self {
+            encryption: None,
+            signing: None,
+            compact_lengths: false,
+            supported_features: 0,
         }
+    }
 
-        let mut two_bytes = [0u8; 2];
-        let mut four_bytes = [0u8; 4];
+    /// Enables AEAD encryption/decryption for payloads with `encrypted != 0`,
+    /// and marks [`features::ENCRYPTED`] as supported.
+    fn with_encryption(mut self, encryption: crypto::EncryptionContext) -> Self {
+        self.encryption = Some(encryption);
+        self.supported_features |= features::ENCRYPTED;
+        self
+    }
 
-        four_bytes.copy_from_slice(&src[..4]);
+    /// Enables Ed25519 signing/verification. Once set, `encode` computes
+    /// `signature`/`signature_length` itself instead of trusting the caller
+    /// to keep them in sync.
+    fn with_signing(mut self, signing: signing::SigningContext) -> Self {
+        self.signing = Some(signing);
+        self
+    }
 
-        let signature_length = u32::from_le_bytes(four_bytes);
+    /// Frames `signature_length`/`payload_length` as BigSize varints instead
+    /// of fixed 4-byte integers, and marks [`features::COMPACT_LENGTHS`] as
+    /// supported.
+    fn with_compact_lengths(mut self) -> Self {
+        self.compact_lengths = true;
+        self.supported_features |= features::COMPACT_LENGTHS;
+        self
+    }
 
-        let version = src[4];
+    /// Marks additional feature bits (see [`features`]) as supported, beyond
+    /// whatever `with_encryption`/`with_compact_lengths` already imply.
+    fn with_features(mut self, supported: u32) -> Self {
+        self.supported_features |= supported;
+        self
+    }
 
-        two_bytes.copy_from_slice(&src[5..7]);
+    /// Serializes the header fields (everything but `data` and `signature`)
+    /// in wire order, honoring `compact_lengths`. Used as AEAD associated
+    /// data and as the payload that gets signed.
+    fn header_bytes(&self, item: &Message) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::SIZE_OF_WITHOUT_VECS);
+        item.write_header(self, &mut buf);
+        buf
+    }
 
-        let message_type = u16::from_le_bytes(two_bytes);
+    /// Writes `signature_length`/`payload_length`, as a BigSize varint when
+    /// `compact_lengths` is set or as a fixed 4-byte little-endian integer
+    /// otherwise.
+    fn write_length<W: Writer>(&self, value: u32, w: &mut W) {
+        if self.compact_lengths {
+            varint::write(value as u64, w);
+        } else {
+            w.write_u32(value);
+        }
+    }
 
-        two_bytes.copy_from_slice(&src[7..9]);
+    /// Reads `signature_length`/`payload_length`, as a BigSize varint when
+    /// `compact_lengths` is set or as a fixed 4-byte little-endian integer
+    /// otherwise.
+    fn read_length<R: Reader>(&self, r: &mut R) -> Result<u32, DecodeError> {
+        if self.compact_lengths {
+            let value = varint::read(r)?;
+            u32::try_from(value).map_err(|_| DecodeError::BadLengthDesc)
+        } else {
+            r.read_u32()
+        }
+    }
 
-        let service_id = u16::from_le_bytes(two_bytes);
+    /// Attempts to parse one frame out of `r`.
+    ///
+    /// Returns `Err(DecodeError::ShortRead)` when `r` does not yet hold a full frame; callers
+    /// (i.e. [`Decoder::decode`]) translate that back into `Ok(None)`.
+    fn try_decode<R: Reader>(&mut self, r: &mut R) -> Result<Message, DecodeError> {
+        let mut item = Message::read_header(self, r)?;
 
-        four_bytes.copy_from_slice(&src[9..13]);
+        if item.version != Self::PROTOCOL_VERSION {
+            return Err(DecodeError::UnknownVersion(item.version));
+        }
 
-        let payload_length = u32::from_le_bytes(four_bytes);
+        let unknown_required = features::unknown_required(item.features, self.supported_features);
+        if unknown_required != 0 {
+            return Err(DecodeError::UnknownRequiredFeature(unknown_required));
+        }
 
-        two_bytes.copy_from_slice(&src[13..15]);
+        if item.payload_length as usize > Self::MAX_FRAME_BODY
+            || item.signature_length as usize > Self::MAX_FRAME_BODY
+        {
+            return Err(DecodeError::BadLengthDesc);
+        }
 
-        let encrypted = u16::from_le_bytes(two_bytes);
+        item.data = r.read_exact(item.payload_length as usize)?;
+        item.signature = r.read_exact(item.signature_length as usize)?;
 
-        let delimiter = src.len() - signature_length as usize;
-        let data = src[15..delimiter].to_vec();
-        let signature = src[delimiter..].to_vec();
+        if let Some(signing) = &self.signing {
+            let header = self.header_bytes(&item);
+            if !signing.verify(item.service_id, &header, &item.data, &item.signature) {
+                return Err(DecodeError::BadSignature);
+            }
+        }
 
-        src.advance(Self::SIZE_OF_WITHOUT_VECS + data.len() + signature.len());
+        if item.encrypted != 0 {
+            let aad = self.header_bytes(&item);
+            let encryption = self
+                .encryption
+                .as_mut()
+                .ok_or(DecodeError::DecryptionFailed)?;
+            item.data = encryption
+                .open(item.service_id, item.nonce, &aad, &item.data)
+                .map_err(|_| DecodeError::DecryptionFailed)?;
+        }
 
-        let item = Self::Item {
-            signature_length,
-            version,
-            message_type,
-            service_id,
-            payload_length,
-            encrypted,
-            data,
-            signature,
-        };
+        Ok(item)
+    }
+}
 
-        Ok(Some(item))
+impl Decoder for MessageCodec {
+    type Item = Message;
+    type Error = DecodeError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // `try_decode` consumes from whatever `Reader` it's given as it goes,
+        // so it runs against a non-consuming `&[u8]` cursor over `src` first
+        // (cheap: re-slicing, not copying) and `src` itself is only advanced
+        // once a full frame is confirmed, leaving it untouched on a short
+        // read. Cloning `src` here instead would copy the entire buffered
+        // frame on every call `Framed` makes while a large frame is still
+        // trickling in.
+        let mut cursor: &[u8] = &src[..];
+        match self.try_decode(&mut cursor) {
+            Ok(item) => {
+                let consumed = src.len() - cursor.len();
+                src.advance(consumed);
+                Ok(Some(item))
+            }
+            Err(DecodeError::ShortRead) => Ok(None),
+            Err(err) => Err(err),
+        }
     }
 }
 
 impl Encoder<Message> for MessageCodec {
-    type Error = std::io::Error;
+    type Error = EncodeError;
+
+    fn encode(&mut self, mut item: Message, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        if self.signing.is_none() && item.signature_length as usize != item.signature.len() {
+            return Err(EncodeError::SignatureLengthMismatch {
+                expected: item.signature_length,
+                actual: item.signature.len(),
+            });
+        }
 
-    fn encode(&mut self, item: Message, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        if item.signature_length as usize != item.signature.len() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Field signature_length doesnt equal singature.len()",
-            ));
+        // Stamp in the feature bits this message actually exercises. A
+        // receiver that doesn't support the required `ENCRYPTED` bit rejects
+        // the message instead of handing back ciphertext; the optional
+        // `COMPACT_LENGTHS` bit only records which framing was used.
+        if self.compact_lengths {
+            item.features |= features::COMPACT_LENGTHS;
+        }
+        if item.encrypted != 0 {
+            item.features |= features::ENCRYPTED;
         }
 
-        dst.reserve(Self::SIZE_OF_WITHOUT_VECS + item.data.len() + item.signature.len());
+        // `signature_length` is fixed by signing being configured at all, so
+        // it's pinned down before the encryption AAD is computed below —
+        // both sides need to agree on the exact header bytes used as AAD,
+        // and the receiver only ever sees the post-signing value off the
+        // wire.
+        if self.signing.is_some() {
+            item.signature_length = signing::SIGNATURE_LEN as u32;
+        }
 
-        dst.extend_from_slice(&item.signature_length.to_le_bytes());
-        dst.extend_from_slice(&item.version.to_le_bytes());
-        dst.extend_from_slice(&item.message_type.to_le_bytes());
-        dst.extend_from_slice(&item.service_id.to_le_bytes());
-        dst.extend_from_slice(&item.payload_length.to_le_bytes());
-        dst.extend_from_slice(&item.encrypted.to_le_bytes());
+        if item.encrypted != 0 {
+            if self.encryption.is_none() {
+                return Err(EncodeError::EncryptionNotConfigured);
+            }
+            // The header (and therefore the AAD) carries the nonce, so the
+            // counter has to be reserved before the header can be computed.
+            let counter = self
+                .encryption
+                .as_mut()
+                .expect("checked above")
+                .reserve_nonce(item.service_id)
+                .map_err(|_| EncodeError::EncryptionNotConfigured)?;
+            item.nonce = counter;
+            let aad = self.header_bytes(&item);
+            let ciphertext = self
+                .encryption
+                .as_mut()
+                .expect("checked above")
+                .seal_at(item.service_id, counter, &aad, &item.data)
+                .map_err(|_| EncodeError::EncryptionNotConfigured)?;
+            item.data = ciphertext;
+        }
+
+        if let Some(signing) = &self.signing {
+            // Signs the final (post-encryption) header and payload, so the
+            // signature covers exactly the bytes a receiver will see.
+            let header = self.header_bytes(&item);
+            item.signature = signing.sign(&header, &item.data);
+        }
+
+        let header = self.header_bytes(&item);
+
+        dst.reserve(header.len() + item.data.len() + item.signature.len());
+        dst.extend_from_slice(&header);
         dst.extend_from_slice(&item.data);
         dst.extend_from_slice(&item.signature);
 
@@ -107,8 +305,16 @@ impl Encoder<Message> for MessageCodec {
     }
 }
 
+fn main() {
+    println!("Hello, PPR!");
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::crypto::{EncryptionContext, PeerStore, RekeyPolicy, StaticKeypair};
+    use crate::error::DecodeError;
+    use crate::signing::{SigningContext, SigningKeypair, TrustedSigners};
+    use crate::features;
     use crate::Message;
     use crate::MessageCodec;
     use bytes::BytesMut;
@@ -121,10 +327,12 @@ mod tests {
         let msg = Message {
             signature_length: 3,
             version: 5,
+            features: 0,
             message_type: 12,
             service_id: 9,
-            payload_length: 11,
-            encrypted: 10,
+            payload_length: 4,
+            encrypted: 0,
+            nonce: 0,
             data: vec![3u8; 4],
             signature: vec![2u8; 3],
         };
@@ -134,12 +342,14 @@ mod tests {
         assert_eq!(bytes.len(), MessageCodec::SIZE_OF_WITHOUT_VECS + 7);
         assert_eq!(bytes[..4], 3_u32.to_le_bytes());
         assert_eq!(bytes[4..5], 5_u8.to_le_bytes());
-        assert_eq!(bytes[5..7], 12_u16.to_le_bytes());
-        assert_eq!(bytes[7..9], 9_u16.to_le_bytes());
-        assert_eq!(bytes[9..13], 11_u32.to_le_bytes());
-        assert_eq!(bytes[13..15], 10_u16.to_le_bytes());
-        assert_eq!(bytes[15..19], [3u8; 4]);
-        assert_eq!(bytes[19..], [2u8; 3]);
+        assert_eq!(bytes[5..9], 0_u32.to_le_bytes());
+        assert_eq!(bytes[9..11], 12_u16.to_le_bytes());
+        assert_eq!(bytes[11..13], 9_u16.to_le_bytes());
+        assert_eq!(bytes[13..17], 4_u32.to_le_bytes());
+        assert_eq!(bytes[17..19], 0_u16.to_le_bytes());
+        assert_eq!(bytes[19..27], 0_u64.to_le_bytes());
+        assert_eq!(bytes[27..31], [3u8; 4]);
+        assert_eq!(bytes[31..], [2u8; 3]);
     }
 
     #[test]
@@ -149,10 +359,12 @@ mod tests {
         let msg = Message {
             signature_length: 3,
             version: 5,
+            features: 0,
             message_type: 12,
             service_id: 9,
-            payload_length: 11,
-            encrypted: 10,
+            payload_length: 4,
+            encrypted: 0,
+            nonce: 0,
             data: vec![3u8; 4],
             signature: vec![2u8; 3],
         };
@@ -163,10 +375,12 @@ mod tests {
 
         assert_eq!(msg.signature_length, 3_u32);
         assert_eq!(msg.version, 5_u8);
+        assert_eq!(msg.features, 0_u32);
         assert_eq!(msg.message_type, 12_u16);
         assert_eq!(msg.service_id, 9_u16);
-        assert_eq!(msg.payload_length, 11_u32);
-        assert_eq!(msg.encrypted, 10_u16);
+        assert_eq!(msg.payload_length, 4_u32);
+        assert_eq!(msg.encrypted, 0_u16);
+        assert_eq!(msg.nonce, 0_u64);
         assert_eq!(msg.data, [3u8; 4]);
         assert_eq!(msg.signature, [2u8; 3]);
     }
@@ -178,20 +392,588 @@ mod tests {
         let msg = Message {
             signature_length: 5,
             version: 5,
+            features: 0,
             message_type: 12,
             service_id: 9,
-            payload_length: 11,
-            encrypted: 10,
+            payload_length: 4,
+            encrypted: 0,
+            nonce: 0,
             data: vec![3u8; 4],
             signature: vec![2u8; 3],
         };
 
-        if let Ok(_) = codec.encode(msg, &mut bytes) {
+        if codec.encode(msg, &mut bytes).is_ok() {
             panic!();
         }
     }
-}
 
-fn main() {
-    println!("Hello, PPR!");
+    /// A message carrying a `version` this codec doesn't understand is
+    /// rejected outright, rather than parsed against a header layout it
+    /// wasn't written for.
+    #[test]
+    fn decode_rejects_unknown_version() {
+        let mut codec = MessageCodec::new();
+        let msg = Message {
+            signature_length: 3,
+            version: 200,
+            features: 0,
+            message_type: 12,
+            service_id: 9,
+            payload_length: 4,
+            encrypted: 0,
+            nonce: 0,
+            data: vec![3u8; 4],
+            signature: vec![2u8; 3],
+        };
+
+        let mut bytes = BytesMut::new();
+        codec.encode(msg, &mut bytes).unwrap();
+
+        match codec.decode(&mut bytes) {
+            Err(DecodeError::UnknownVersion(200)) => {}
+            other => panic!("expected UnknownVersion(200), got {other:?}"),
+        }
+    }
+
+    /// `decode` must leave `src` untouched on a short read (the tokio
+    /// `Decoder` contract), even though `try_decode` consumes from whatever
+    /// `Reader` it's given as it goes — this is what the non-consuming
+    /// `&[u8]` cursor in `Decoder::decode` is for.
+    #[test]
+    fn decode_handles_fragmented_input() {
+        let mut codec = MessageCodec::new();
+        let msg = Message {
+            signature_length: 3,
+            version: 5,
+            features: 0,
+            message_type: 12,
+            service_id: 9,
+            payload_length: 4,
+            encrypted: 0,
+            nonce: 0,
+            data: vec![3u8; 4],
+            signature: vec![2u8; 3],
+        };
+
+        let mut whole = BytesMut::new();
+        codec.encode(msg, &mut whole).unwrap();
+
+        let split_at = whole.len() - 3;
+        let tail = whole.split_off(split_at);
+        let mut bytes = whole;
+
+        assert!(codec.decode(&mut bytes).unwrap().is_none());
+        assert_eq!(bytes.len(), split_at, "a short read must not consume `src`");
+
+        bytes.extend_from_slice(&tail);
+        let decoded = codec.decode(&mut bytes).unwrap().unwrap();
+
+        assert_eq!(decoded.data, [3u8; 4]);
+        assert_eq!(decoded.signature, [2u8; 3]);
+    }
+
+    /// `IoReader` adapts a blocking `std::io::Read` the same way `&[u8]` and
+    /// `BytesMut` are adapted, reporting a truncated frame as `ShortRead`
+    /// rather than an I/O error.
+    #[test]
+    fn io_reader_reads_a_full_frame_and_reports_truncation_as_short_read() {
+        use crate::io::IoReader;
+
+        let mut codec = MessageCodec::new();
+        let msg = Message {
+            signature_length: 3,
+            version: 5,
+            features: 0,
+            message_type: 12,
+            service_id: 9,
+            payload_length: 4,
+            encrypted: 0,
+            nonce: 0,
+            data: vec![3u8; 4],
+            signature: vec![2u8; 3],
+        };
+
+        let mut bytes = BytesMut::new();
+        codec.encode(msg, &mut bytes).unwrap();
+
+        let mut reader = IoReader(&bytes[..]);
+        let decoded = codec.try_decode(&mut reader).unwrap();
+        assert_eq!(decoded.data, [3u8; 4]);
+        assert_eq!(decoded.signature, [2u8; 3]);
+
+        let truncated = &bytes[..bytes.len() - 1];
+        let mut reader = IoReader(truncated);
+        match codec.try_decode(&mut reader) {
+            Err(DecodeError::ShortRead) => {}
+            other => panic!("expected ShortRead, got {other:?}"),
+        }
+    }
+
+    /// A genuine I/O failure (as opposed to the stream simply not having the
+    /// rest of the frame yet) must surface as `DecodeError::Io`, not
+    /// `ShortRead` — a caller that retries on `ShortRead` would otherwise
+    /// spin forever against a transport that has actually errored out.
+    #[test]
+    fn io_reader_reports_genuine_io_errors_distinctly_from_short_read() {
+        use crate::io::IoReader;
+        use std::io::{Error, ErrorKind, Read};
+
+        struct FailingReader;
+
+        impl Read for FailingReader {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(Error::new(ErrorKind::PermissionDenied, "denied"))
+            }
+        }
+
+        let mut codec = MessageCodec::new();
+        let mut reader = IoReader(FailingReader);
+        match codec.try_decode(&mut reader) {
+            Err(DecodeError::Io(ErrorKind::PermissionDenied)) => {}
+            other => panic!("expected Io(PermissionDenied), got {other:?}"),
+        }
+    }
+
+    /// Both sides derive the same traffic key in `SharedSecret` mode, so a
+    /// message marked `encrypted` round-trips through encode/decode back to
+    /// its original plaintext.
+    #[test]
+    fn encrypted_message_round_trips() {
+        const SERVICE_ID: u16 = 9;
+
+        let sender_key = StaticKeypair::from_passphrase("correct horse battery staple");
+        let sender_peers = PeerStore::new().trust_self(SERVICE_ID, &sender_key);
+        let mut sender = MessageCodec::new().with_encryption(EncryptionContext::new(
+            sender_key,
+            sender_peers,
+            RekeyPolicy::default(),
+        ));
+
+        let receiver_key = StaticKeypair::from_passphrase("correct horse battery staple");
+        let receiver_peers = PeerStore::new().trust_self(SERVICE_ID, &receiver_key);
+        let mut receiver = MessageCodec::new().with_encryption(EncryptionContext::new(
+            receiver_key,
+            receiver_peers,
+            RekeyPolicy::default(),
+        ));
+
+        let msg = Message {
+            signature_length: 0,
+            version: 5,
+            features: 0,
+            message_type: 12,
+            service_id: SERVICE_ID,
+            payload_length: 4 + 16,
+            encrypted: 1,
+            nonce: 0,
+            data: vec![3u8; 4],
+            signature: Vec::new(),
+        };
+
+        let mut bytes = BytesMut::new();
+        sender.encode(msg, &mut bytes).unwrap();
+
+        let decoded = receiver.decode(&mut bytes).unwrap().unwrap();
+
+        assert_eq!(decoded.data, vec![3u8; 4]);
+    }
+
+    /// Two distinct peers exchanging messages in both directions each start
+    /// their own nonce counter at 0. Each side decrypts what the other sent,
+    /// and two frames with the same plaintext, header and nonce still differ
+    /// on the wire, which holds only because the traffic key is split by
+    /// direction.
+    #[test]
+    fn bidirectional_messages_use_distinct_keys_per_direction() {
+        const SERVICE_ID: u16 = 9;
+
+        let node_a_key = StaticKeypair::generate();
+        let node_a_public = node_a_key.public();
+        let node_b_key = StaticKeypair::generate();
+        let node_b_public = node_b_key.public();
+
+        let mut node_a = MessageCodec::new().with_encryption(EncryptionContext::new(
+            node_a_key,
+            PeerStore::new().trust(SERVICE_ID, node_b_public),
+            RekeyPolicy::default(),
+        ));
+        let mut node_b = MessageCodec::new().with_encryption(EncryptionContext::new(
+            node_b_key,
+            PeerStore::new().trust(SERVICE_ID, node_a_public),
+            RekeyPolicy::default(),
+        ));
+
+        let message_for = |data: Vec<u8>| Message {
+            signature_length: 0,
+            version: 5,
+            features: 0,
+            message_type: 12,
+            service_id: SERVICE_ID,
+            payload_length: data.len() as u32 + 16,
+            encrypted: 1,
+            nonce: 0,
+            data,
+            signature: Vec::new(),
+        };
+
+        let mut a_to_b = BytesMut::new();
+        node_a.encode(message_for(vec![1u8; 4]), &mut a_to_b).unwrap();
+        let mut b_to_a = BytesMut::new();
+        node_b.encode(message_for(vec![2u8; 4]), &mut b_to_a).unwrap();
+
+        assert_eq!(node_b.decode(&mut a_to_b).unwrap().unwrap().data, vec![1u8; 4]);
+        assert_eq!(node_a.decode(&mut b_to_a).unwrap().unwrap().data, vec![2u8; 4]);
+
+        // Same plaintext and, at nonce 1 on both sides, an identical header:
+        // the two frames can only differ if the directional keys do.
+        let mut a_again = BytesMut::new();
+        node_a.encode(message_for(vec![7u8; 4]), &mut a_again).unwrap();
+        let mut b_again = BytesMut::new();
+        node_b.encode(message_for(vec![7u8; 4]), &mut b_again).unwrap();
+        assert_ne!(a_again, b_again);
+    }
+
+    /// Replaying an already-decoded ciphertext (same nonce, same bytes) is
+    /// rejected instead of being decrypted again.
+    #[test]
+    fn decode_rejects_replayed_nonce() {
+        const SERVICE_ID: u16 = 9;
+
+        let sender_key = StaticKeypair::from_passphrase("correct horse battery staple");
+        let sender_peers = PeerStore::new().trust_self(SERVICE_ID, &sender_key);
+        let mut sender = MessageCodec::new().with_encryption(EncryptionContext::new(
+            sender_key,
+            sender_peers,
+            RekeyPolicy::default(),
+        ));
+
+        let receiver_key = StaticKeypair::from_passphrase("correct horse battery staple");
+        let receiver_peers = PeerStore::new().trust_self(SERVICE_ID, &receiver_key);
+        let mut receiver = MessageCodec::new().with_encryption(EncryptionContext::new(
+            receiver_key,
+            receiver_peers,
+            RekeyPolicy::default(),
+        ));
+
+        let msg = Message {
+            signature_length: 0,
+            version: 5,
+            features: 0,
+            message_type: 12,
+            service_id: SERVICE_ID,
+            payload_length: 4 + 16,
+            encrypted: 1,
+            nonce: 0,
+            data: vec![3u8; 4],
+            signature: Vec::new(),
+        };
+
+        let mut bytes = BytesMut::new();
+        sender.encode(msg, &mut bytes).unwrap();
+        let mut replayed = bytes.clone();
+
+        assert!(receiver.decode(&mut bytes).unwrap().is_some());
+        assert!(receiver.decode(&mut replayed).is_err());
+    }
+
+    /// A message that arrives out of order (not a replay — just delayed
+    /// behind later ones) must still decrypt, per the module's stated goal
+    /// that reordered or dropped messages still decrypt. Nonces 0, 2, 1
+    /// arriving in that order must all succeed; only an actual repeat of an
+    /// already-accepted nonce is rejected.
+    #[test]
+    fn decode_tolerates_reordered_messages() {
+        const SERVICE_ID: u16 = 9;
+
+        let sender_key = StaticKeypair::from_passphrase("correct horse battery staple");
+        let sender_peers = PeerStore::new().trust_self(SERVICE_ID, &sender_key);
+        let mut sender = MessageCodec::new().with_encryption(EncryptionContext::new(
+            sender_key,
+            sender_peers,
+            RekeyPolicy::default(),
+        ));
+
+        let receiver_key = StaticKeypair::from_passphrase("correct horse battery staple");
+        let receiver_peers = PeerStore::new().trust_self(SERVICE_ID, &receiver_key);
+        let mut receiver = MessageCodec::new().with_encryption(EncryptionContext::new(
+            receiver_key,
+            receiver_peers,
+            RekeyPolicy::default(),
+        ));
+
+        let message_for = |data: Vec<u8>| Message {
+            signature_length: 0,
+            version: 5,
+            features: 0,
+            message_type: 12,
+            service_id: SERVICE_ID,
+            payload_length: data.len() as u32 + 16,
+            encrypted: 1,
+            nonce: 0,
+            data,
+            signature: Vec::new(),
+        };
+
+        // Encode three messages in order, so their nonces are 0, 1, 2.
+        let mut frame_0 = BytesMut::new();
+        sender.encode(message_for(vec![0u8; 4]), &mut frame_0).unwrap();
+        let mut frame_1 = BytesMut::new();
+        sender.encode(message_for(vec![1u8; 4]), &mut frame_1).unwrap();
+        let mut frame_2 = BytesMut::new();
+        sender.encode(message_for(vec![2u8; 4]), &mut frame_2).unwrap();
+
+        // Deliver them out of order: 0, 2, 1.
+        assert_eq!(receiver.decode(&mut frame_0).unwrap().unwrap().data, vec![0u8; 4]);
+        assert_eq!(receiver.decode(&mut frame_2).unwrap().unwrap().data, vec![2u8; 4]);
+        assert_eq!(receiver.decode(&mut frame_1).unwrap().unwrap().data, vec![1u8; 4]);
+    }
+
+    /// A dropped message must not desync which rekey generation the two
+    /// sides use for everything after it. The middle of three messages sent
+    /// across a rekey boundary (`max_messages: 2`) is dropped and never
+    /// reaches the receiver; the third message (already in the next
+    /// generation, from the sender's perspective) must still decrypt, which
+    /// only holds if the receiver derives its key from the message's own
+    /// nonce rather than from a local count of messages it has received.
+    #[test]
+    fn dropped_message_does_not_desync_rekey_generation() {
+        const SERVICE_ID: u16 = 9;
+        let policy = RekeyPolicy { max_messages: 2 };
+
+        let sender_key = StaticKeypair::from_passphrase("correct horse battery staple");
+        let sender_peers = PeerStore::new().trust_self(SERVICE_ID, &sender_key);
+        let mut sender =
+            MessageCodec::new().with_encryption(EncryptionContext::new(sender_key, sender_peers, policy));
+
+        let receiver_key = StaticKeypair::from_passphrase("correct horse battery staple");
+        let receiver_peers = PeerStore::new().trust_self(SERVICE_ID, &receiver_key);
+        let mut receiver = MessageCodec::new().with_encryption(EncryptionContext::new(
+            receiver_key,
+            receiver_peers,
+            policy,
+        ));
+
+        let message_for = |data: Vec<u8>| Message {
+            signature_length: 0,
+            version: 5,
+            features: 0,
+            message_type: 12,
+            service_id: SERVICE_ID,
+            payload_length: data.len() as u32 + 16,
+            encrypted: 1,
+            nonce: 0,
+            data,
+            signature: Vec::new(),
+        };
+
+        // The sender sends three messages; the third (nonce 2) falls into
+        // the next rekey generation under `max_messages: 2`. The second
+        // (nonce 1) is dropped in transit and never reaches
+        // `receiver.decode`, so the receiver jumps straight from generation
+        // 0 to generation 1 without ever having "counted" its way there.
+        let mut frame_0 = BytesMut::new();
+        sender.encode(message_for(vec![0u8; 4]), &mut frame_0).unwrap();
+        let mut _dropped = BytesMut::new();
+        sender.encode(message_for(vec![1u8; 4]), &mut _dropped).unwrap();
+        let mut frame_2 = BytesMut::new();
+        sender.encode(message_for(vec![2u8; 4]), &mut frame_2).unwrap();
+
+        assert_eq!(receiver.decode(&mut frame_0).unwrap().unwrap().data, vec![0u8; 4]);
+        assert_eq!(receiver.decode(&mut frame_2).unwrap().unwrap().data, vec![2u8; 4]);
+    }
+
+    /// When both encryption and signing are configured, `signature_length`
+    /// must be pinned to its final value before the encryption AAD is
+    /// computed, or the sender's and receiver's AAD disagree (the receiver
+    /// only ever sees the post-signing header) and `open()` always fails.
+    #[test]
+    fn encrypted_and_signed_message_round_trips() {
+        const SERVICE_ID: u16 = 9;
+
+        let sender_encryption_key = StaticKeypair::from_passphrase("correct horse battery staple");
+        let sender_peers = PeerStore::new().trust_self(SERVICE_ID, &sender_encryption_key);
+        let signer_key = SigningKeypair::generate();
+        let trusted = TrustedSigners::new().trust(SERVICE_ID, signer_key.verifying_key());
+        let mut sender = MessageCodec::new()
+            .with_encryption(EncryptionContext::new(
+                sender_encryption_key,
+                sender_peers,
+                RekeyPolicy::default(),
+            ))
+            .with_signing(SigningContext::new(signer_key, TrustedSigners::new()));
+
+        let receiver_encryption_key = StaticKeypair::from_passphrase("correct horse battery staple");
+        let receiver_peers = PeerStore::new().trust_self(SERVICE_ID, &receiver_encryption_key);
+        let mut receiver = MessageCodec::new()
+            .with_encryption(EncryptionContext::new(
+                receiver_encryption_key,
+                receiver_peers,
+                RekeyPolicy::default(),
+            ))
+            .with_signing(SigningContext::new(SigningKeypair::generate(), trusted));
+
+        let msg = Message {
+            signature_length: 0,
+            version: 5,
+            features: 0,
+            message_type: 12,
+            service_id: SERVICE_ID,
+            payload_length: 4 + 16,
+            encrypted: 1,
+            nonce: 0,
+            data: vec![3u8; 4],
+            signature: Vec::new(),
+        };
+
+        let mut bytes = BytesMut::new();
+        sender.encode(msg, &mut bytes).unwrap();
+
+        let decoded = receiver.decode(&mut bytes).unwrap().unwrap();
+
+        assert_eq!(decoded.data, vec![3u8; 4]);
+    }
+
+    /// An encrypted message sent to a receiver that hasn't configured
+    /// encryption is rejected for its unsupported required feature bit,
+    /// instead of reaching (and failing) decryption.
+    #[test]
+    fn decode_rejects_unknown_required_feature() {
+        const SERVICE_ID: u16 = 9;
+
+        let sender_key = StaticKeypair::from_passphrase("correct horse battery staple");
+        let sender_peers = PeerStore::new().trust_self(SERVICE_ID, &sender_key);
+        let mut sender = MessageCodec::new().with_encryption(EncryptionContext::new(
+            sender_key,
+            sender_peers,
+            RekeyPolicy::default(),
+        ));
+        let mut receiver = MessageCodec::new();
+
+        let msg = Message {
+            signature_length: 0,
+            version: 5,
+            features: 0,
+            message_type: 12,
+            service_id: SERVICE_ID,
+            payload_length: 4 + 16,
+            encrypted: 1,
+            nonce: 0,
+            data: vec![3u8; 4],
+            signature: Vec::new(),
+        };
+
+        let mut bytes = BytesMut::new();
+        sender.encode(msg, &mut bytes).unwrap();
+
+        match receiver.decode(&mut bytes) {
+            Err(DecodeError::UnknownRequiredFeature(bits)) => {
+                assert_eq!(bits, features::ENCRYPTED)
+            }
+            other => panic!("expected UnknownRequiredFeature, got {other:?}"),
+        }
+    }
+
+    /// `encode` computes `signature`/`signature_length` itself when signing
+    /// is configured, and `decode` accepts the result against the trusted key.
+    #[test]
+    fn signed_message_round_trips() {
+        const SERVICE_ID: u16 = 9;
+
+        let signer_key = SigningKeypair::generate();
+        let trusted = TrustedSigners::new().trust(SERVICE_ID, signer_key.verifying_key());
+
+        let mut sender =
+            MessageCodec::new().with_signing(SigningContext::new(signer_key, TrustedSigners::new()));
+        let mut receiver = MessageCodec::new()
+            .with_signing(SigningContext::new(SigningKeypair::generate(), trusted));
+
+        let msg = Message {
+            signature_length: 0,
+            version: 5,
+            features: 0,
+            message_type: 12,
+            service_id: SERVICE_ID,
+            payload_length: 4,
+            encrypted: 0,
+            nonce: 0,
+            data: vec![3u8; 4],
+            signature: Vec::new(),
+        };
+
+        let mut bytes = BytesMut::new();
+        sender.encode(msg, &mut bytes).unwrap();
+
+        let decoded = receiver.decode(&mut bytes).unwrap().unwrap();
+
+        assert_eq!(decoded.signature.len(), crate::signing::SIGNATURE_LEN);
+        assert_eq!(decoded.data, vec![3u8; 4]);
+    }
+
+    /// A message signed by an untrusted key is rejected with `BadSignature`
+    /// instead of being handed to the caller.
+    #[test]
+    fn decode_rejects_untrusted_signature() {
+        const SERVICE_ID: u16 = 9;
+
+        let mut sender = MessageCodec::new().with_signing(SigningContext::new(
+            SigningKeypair::generate(),
+            TrustedSigners::new(),
+        ));
+        // The receiver trusts a different key than the one that actually signed.
+        let trusted = TrustedSigners::new().trust(SERVICE_ID, SigningKeypair::generate().verifying_key());
+        let mut receiver =
+            MessageCodec::new().with_signing(SigningContext::new(SigningKeypair::generate(), trusted));
+
+        let msg = Message {
+            signature_length: 0,
+            version: 5,
+            features: 0,
+            message_type: 12,
+            service_id: SERVICE_ID,
+            payload_length: 4,
+            encrypted: 0,
+            nonce: 0,
+            data: vec![3u8; 4],
+            signature: Vec::new(),
+        };
+
+        let mut bytes = BytesMut::new();
+        sender.encode(msg, &mut bytes).unwrap();
+
+        assert!(receiver.decode(&mut bytes).is_err());
+    }
+
+    /// With `compact_lengths` on, a small `payload_length`/`signature_length`
+    /// each collapse to a single BigSize byte instead of 4.
+    #[test]
+    fn compact_lengths_round_trip_and_shrink_header() {
+        let mut bytes = BytesMut::new();
+        let mut codec = MessageCodec::new().with_compact_lengths();
+        let msg = Message {
+            signature_length: 3,
+            version: 5,
+            features: 0,
+            message_type: 12,
+            service_id: 9,
+            payload_length: 4,
+            encrypted: 0,
+            nonce: 0,
+            data: vec![3u8; 4],
+            signature: vec![2u8; 3],
+        };
+
+        codec.encode(msg, &mut bytes).unwrap();
+
+        // signature_length(1) + version(1) + features(4) + message_type(2)
+        // + service_id(2) + payload_length(1) + encrypted(2) + nonce(8) = 21,
+        // vs. 27 fixed-width.
+        assert_eq!(bytes.len(), 21 + 7);
+
+        let decoded = codec.decode(&mut bytes).unwrap().unwrap();
+
+        assert_eq!(decoded.signature_length, 3_u32);
+        assert_eq!(decoded.payload_length, 4_u32);
+        assert_eq!(decoded.data, [3u8; 4]);
+        assert_eq!(decoded.signature, [2u8; 3]);
+    }
 }
@@ -0,0 +1,97 @@
+//! A declarative description of the message header, so adding, removing, or
+//! reordering a field means editing one macro invocation instead of
+//! re-deriving the byte offsets of everything after it by hand.
+//!
+//! [`define_header!`] takes the header as an ordered list of `scalar` fields
+//! (fixed-width little-endian integers) and `length` fields
+//! (`signature_length`/`payload_length`, framed as either a BigSize varint
+//! or a fixed integer depending on `MessageCodec::compact_lengths`) and
+//! generates `write_header`/`read_header`, which walk the fields in
+//! declaration order against the [`crate::io::Writer`]/[`crate::io::Reader`]
+//! abstraction. The two length-prefixed vectors (`data`, `signature`) are
+//! handled separately by the codec, since their sizes are *derived* from the
+//! length fields rather than present on the wire themselves.
+
+use crate::error::DecodeError;
+use crate::io::{Reader, Writer};
+
+/// A fixed-width integer that can be framed as little-endian bytes.
+pub trait WireScalar: Sized {
+    fn write_le<W: Writer>(&self, w: &mut W);
+    fn read_le<R: Reader>(r: &mut R) -> Result<Self, DecodeError>;
+}
+
+macro_rules! impl_wire_scalar {
+    ($ty:ty, $write_method:ident, $read_method:ident) => {
+        impl WireScalar for $ty {
+            fn write_le<W: Writer>(&self, w: &mut W) {
+                w.$write_method(*self);
+            }
+
+            fn read_le<R: Reader>(r: &mut R) -> Result<Self, DecodeError> {
+                r.$read_method()
+            }
+        }
+    };
+}
+
+impl_wire_scalar!(u8, write_u8, read_u8);
+impl_wire_scalar!(u16, write_u16, read_u16);
+impl_wire_scalar!(u32, write_u32, read_u32);
+impl_wire_scalar!(u64, write_u64, read_u64);
+
+/// Declares `write_header`/`read_header` for a header field list. See the
+/// module docs for the `scalar`/`length` field kinds.
+///
+/// `self`/`codec`/`w`/`r` are captured once, in the single top-level match
+/// arm below, and forwarded into the `@write`/`@read` helper arms as tokens
+/// rather than re-spelled as fresh identifiers in each of them. Declarative
+/// macros give every *literal* identifier written in a macro arm its own
+/// hygiene context per expansion, so a tt-muncher that re-wrote `self`/
+/// `codec`/`w`/`r` inside a per-field recursive arm would produce bindings
+/// that look identical but don't actually resolve to the same thing —
+/// forwarding the tokens that are already bound is what keeps them resolving
+/// to `write_header`'s/`read_header`'s real parameters.
+macro_rules! define_header {
+    ( fields { $( $kind:ident $field:ident : $ty:ty, )* } ) => {
+        /// Writes every header field, in declaration order.
+        fn write_header<W: $crate::io::Writer>(&self, codec: &crate::MessageCodec, w: &mut W) {
+            $(
+                $crate::schema::define_header!(@write $kind, self, codec, w, $field);
+            )*
+        }
+
+        /// Reads every header field, in declaration order. `data`/
+        /// `signature` are left empty; the caller fills them in from the
+        /// derived lengths.
+        fn read_header<R: $crate::io::Reader>(
+            codec: &crate::MessageCodec,
+            r: &mut R,
+        ) -> Result<Self, $crate::error::DecodeError> {
+            $(
+                let $field: $ty = $crate::schema::define_header!(@read $kind, codec, r, $field, $ty);
+            )*
+            Ok(Self {
+                $($field,)*
+                data: Vec::new(),
+                signature: Vec::new(),
+            })
+        }
+    };
+
+    (@write length, $self:tt, $codec:tt, $w:tt, $field:ident) => {
+        $codec.write_length($self.$field, $w);
+    };
+    (@write scalar, $self:tt, $codec:tt, $w:tt, $field:ident) => {
+        $crate::schema::WireScalar::write_le(&$self.$field, $w);
+    };
+
+    (@read length, $codec:tt, $r:tt, $field:ident, $ty:ty) => {
+        $codec.read_length($r)?
+    };
+    (@read scalar, $codec:tt, $r:tt, $field:ident, $ty:ty) => {
+        <$ty as $crate::schema::WireScalar>::read_le($r)?
+    };
+}
+
+pub(crate) use define_header;
@@ -0,0 +1,96 @@
+//! Ed25519 signing and verification over the framed header and payload.
+//!
+//! This turns the wire format into an authenticated message: [`MessageCodec`]
+//! computes `signature`/`signature_length` on encode instead of trusting the
+//! caller to keep them in sync, and rejects mismatched signatures with
+//! [`crate::error::DecodeError::BadSignature`] on decode.
+
+use std::collections::HashMap;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+/// Length in bytes of an Ed25519 signature; `signature_length` is always
+/// this value for a signed message.
+pub const SIGNATURE_LEN: usize = 64;
+
+/// A node's long-term Ed25519 signing identity.
+pub struct SigningKeypair {
+    signing_key: SigningKey,
+}
+
+impl SigningKeypair {
+    pub fn generate() -> Self {
+        Self {
+            signing_key: SigningKey::generate(&mut rand::rngs::OsRng),
+        }
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.signing_key.verifying_key()
+    }
+
+    fn sign(&self, transcript: &[u8]) -> Signature {
+        self.signing_key.sign(transcript)
+    }
+}
+
+/// Trusted verifying keys, selected by `service_id`.
+#[derive(Default)]
+pub struct TrustedSigners {
+    keys: HashMap<u16, VerifyingKey>,
+}
+
+impl TrustedSigners {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn trust(mut self, service_id: u16, key: VerifyingKey) -> Self {
+        self.keys.insert(service_id, key);
+        self
+    }
+
+    pub fn get(&self, service_id: u16) -> Option<&VerifyingKey> {
+        self.keys.get(&service_id)
+    }
+}
+
+/// Per-codec signing state: our identity plus the peers we trust.
+pub struct SigningContext {
+    local: SigningKeypair,
+    trusted: TrustedSigners,
+}
+
+impl SigningContext {
+    pub fn new(local: SigningKeypair, trusted: TrustedSigners) -> Self {
+        Self { local, trusted }
+    }
+
+    /// Signs `header || data` with the local key.
+    pub fn sign(&self, header: &[u8], data: &[u8]) -> Vec<u8> {
+        let transcript = transcript(header, data);
+        self.local.sign(&transcript).to_bytes().to_vec()
+    }
+
+    /// Verifies `signature` over `header || data` against the key trusted for `service_id`.
+    pub fn verify(&self, service_id: u16, header: &[u8], data: &[u8], signature: &[u8]) -> bool {
+        let Some(key) = self.trusted.get(service_id) else {
+            return false;
+        };
+        let Ok(signature_bytes) = <[u8; SIGNATURE_LEN]>::try_from(signature) else {
+            return false;
+        };
+
+        let signature = Signature::from_bytes(&signature_bytes);
+        let transcript = transcript(header, data);
+
+        key.verify(&transcript, &signature).is_ok()
+    }
+}
+
+fn transcript(header: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut transcript = Vec::with_capacity(header.len() + data.len());
+    transcript.extend_from_slice(header);
+    transcript.extend_from_slice(data);
+    transcript
+}
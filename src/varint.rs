@@ -0,0 +1,57 @@
+//! Lightning-style BigSize variable-length integers, used as a compact
+//! alternative to the fixed 4-byte length fields.
+//!
+//! A value below `0xFD` is one byte; `0xFD` introduces a big-endian `u16`,
+//! `0xFE` a big-endian `u32`, and `0xFF` a big-endian `u64`. Encodings that
+//! use a longer prefix than the value requires are rejected so the wire
+//! representation stays unique.
+
+use crate::error::DecodeError;
+use crate::io::{Reader, Writer};
+
+/// Reads one BigSize-encoded integer from `r`.
+pub fn read<R: Reader>(r: &mut R) -> Result<u64, DecodeError> {
+    match r.read_u8()? {
+        0xFF => {
+            let bytes = r.read_exact(8)?;
+            let value = u64::from_be_bytes(bytes.try_into().expect("read_exact(8) returns 8 bytes"));
+            if value <= u32::MAX as u64 {
+                return Err(DecodeError::BadLengthDesc);
+            }
+            Ok(value)
+        }
+        0xFE => {
+            let bytes = r.read_exact(4)?;
+            let value = u32::from_be_bytes(bytes.try_into().expect("read_exact(4) returns 4 bytes")) as u64;
+            if value <= u16::MAX as u64 {
+                return Err(DecodeError::BadLengthDesc);
+            }
+            Ok(value)
+        }
+        0xFD => {
+            let bytes = r.read_exact(2)?;
+            let value = u16::from_be_bytes(bytes.try_into().expect("read_exact(2) returns 2 bytes")) as u64;
+            if value < 0xFD {
+                return Err(DecodeError::BadLengthDesc);
+            }
+            Ok(value)
+        }
+        small => Ok(small as u64),
+    }
+}
+
+/// Encodes `value` as a BigSize, using the shortest canonical form.
+pub fn write<W: Writer>(value: u64, w: &mut W) {
+    if value < 0xFD {
+        w.write_u8(value as u8);
+    } else if value <= u16::MAX as u64 {
+        w.write_u8(0xFD);
+        w.write_bytes(&(value as u16).to_be_bytes());
+    } else if value <= u32::MAX as u64 {
+        w.write_u8(0xFE);
+        w.write_bytes(&(value as u32).to_be_bytes());
+    } else {
+        w.write_u8(0xFF);
+        w.write_bytes(&value.to_be_bytes());
+    }
+}